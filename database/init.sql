@@ -4,6 +4,10 @@ create table if not exists feed (
     title varchar(1000) NOT NULL,
     description text NULL,
     active bool DEFAULT true,
+    muted bool DEFAULT false,
+    min_published timestamptz NULL,
+    reachable bool DEFAULT true,
+    timezone varchar(64) NULL,
     PRIMARY KEY(id)
 );
 
@@ -12,10 +16,18 @@ create table if not exists article (
     feed_id uuid,
     url varchar(2000) NOT NULL,
     title varchar(1000) NOT NULL,
+    summary text NULL,
     content text NOT NULL,
     read bool DEFAULT false,
     published timestamptz NOT NULL,
     PRIMARY KEY(id),
     FOREIGN KEY (feed_id) REFERENCES feed(id),
     UNIQUE(url)
-)
\ No newline at end of file
+);
+
+-- bring databases created before these columns existed up to date
+alter table feed add column if not exists muted bool DEFAULT false;
+alter table feed add column if not exists min_published timestamptz NULL;
+alter table feed add column if not exists reachable bool DEFAULT true;
+alter table feed add column if not exists timezone varchar(64) NULL;
+alter table article add column if not exists summary text NULL;
@@ -4,40 +4,68 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
-use models::{db::Article, rest::ArticleQuery};
-use sqlx::{Pool, Postgres};
+use models::{
+    db::Article,
+    rest::{ArticleDeleteQuery, ArticleDeleteResult, ArticleQuery, ArticleViewQuery},
+};
+use sqlx::{Pool, Postgres, QueryBuilder};
 use uuid::Uuid;
 
+#[utoipa::path(
+    get,
+    path = "/articles",
+    params(ArticleQuery),
+    responses((status = 200, description = "Articles", body = [Article])),
+    tag = "articles"
+)]
 pub async fn list_articles(
     Extension(conn): Extension<Pool<Postgres>>,
     Query(query_params): Query<ArticleQuery>,
 ) -> Response {
     let mut query = " SELECT * FROM article where 1=1 ".to_string();
 
-    if query_params.feed_id.is_some() {
-        query = query + " and feed_id = \'" + &query_params.feed_id.unwrap().to_string() + "\'"
+    if let Some(feed_id) = query_params.feed_id {
+        query = query + " and feed_id = \'" + &feed_id.to_string() + "\'"
+    }
+    // asking for one feed by id is explicit enough to show it even if muted
+    if query_params.feed_id.is_none() && query_params.include_muted != Some(true) {
+        query += " and not exists (SELECT 1 FROM feed f where f.id = article.feed_id and f.muted)"
     }
-    if query_params.unread_only.is_some() {
-        query = query + " and read = " + &(!query_params.unread_only.unwrap()).to_string()
+    if let Some(unread_only) = query_params.unread_only {
+        query = query + " and read = " + &(!unread_only).to_string()
     }
-    if query_params.limit.is_some() {
-        query = query + " limit " + &query_params.limit.unwrap().to_string()
+    if let Some(limit) = query_params.limit {
+        query = query + " limit " + &limit.to_string()
     }
-    if query_params.offset.is_some() {
-        query = query + " offset " + &query_params.offset.unwrap().to_string()
+    if let Some(offset) = query_params.offset {
+        query = query + " offset " + &offset.to_string()
     }
 
     let result = sqlx::query_as::<_, Article>(&query).fetch_all(&conn).await;
 
     match result {
-        Ok(articles) => (StatusCode::OK, Json(articles)).into_response(),
+        Ok(articles) => {
+            let articles: Vec<Article> = articles
+                .into_iter()
+                .map(|article| article.with_view(query_params.view))
+                .collect();
+            (StatusCode::OK, Json(articles)).into_response()
+        }
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/feeds/{id}/articles",
+    params(("id" = Uuid, Path, description = "Feed id"), ArticleViewQuery),
+    responses((status = 200, description = "Articles of the feed", body = [Article])),
+    tag = "articles"
+)]
 pub async fn list_feed_articles(
     Path(feed_id): Path<Uuid>,
     Extension(conn): Extension<Pool<Postgres>>,
+    Query(view_query): Query<ArticleViewQuery>,
 ) -> Response {
     let result = sqlx::query_as::<_, Article>("SELECT * FROM article where feed_id = $1;")
         .bind(feed_id)
@@ -45,14 +73,28 @@ pub async fn list_feed_articles(
         .await;
 
     match result {
-        Ok(articles) => (StatusCode::OK, Json(articles)).into_response(),
+        Ok(articles) => {
+            let articles: Vec<Article> = articles
+                .into_iter()
+                .map(|article| article.with_view(view_query.view))
+                .collect();
+            (StatusCode::OK, Json(articles)).into_response()
+        }
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/articles/{id}",
+    params(("id" = Uuid, Path, description = "Article id"), ArticleViewQuery),
+    responses((status = 200, description = "Article", body = Article)),
+    tag = "articles"
+)]
 pub async fn get_article(
     Path(id): Path<Uuid>,
     Extension(conn): Extension<Pool<Postgres>>,
+    Query(view_query): Query<ArticleViewQuery>,
 ) -> Response {
     let result = sqlx::query_as::<_, Article>("SELECT * FROM article where id = $1;")
         .bind(id)
@@ -60,11 +102,21 @@ pub async fn get_article(
         .await;
 
     match result {
-        Ok(articles) => (StatusCode::OK, Json(articles)).into_response(),
+        Ok(article) => (StatusCode::OK, Json(article.with_view(view_query.view))).into_response(),
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/articles/{id}/read",
+    params(("id" = Uuid, Path, description = "Article id")),
+    responses(
+        (status = 200, description = "Article marked as read"),
+        (status = 404, description = "Article not found")
+    ),
+    tag = "articles"
+)]
 pub async fn article_mark_read(
     Path(id): Path<Uuid>,
     Extension(conn): Extension<Pool<Postgres>>,
@@ -85,3 +137,198 @@ pub async fn article_mark_read(
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
     }
 }
+
+// upper bound on rows removed by a single bulk delete call
+const MAX_BULK_DELETE: i64 = 10_000;
+
+#[utoipa::path(
+    post,
+    path = "/articles/delete",
+    params(ArticleDeleteQuery),
+    responses(
+        (status = 200, description = "Matching articles deleted, up to the per-call cap", body = ArticleDeleteResult),
+        (status = 400, description = "Missing confirm=true or no filter given")
+    ),
+    tag = "articles"
+)]
+pub async fn delete_articles(
+    Extension(conn): Extension<Pool<Postgres>>,
+    Query(filter): Query<ArticleDeleteQuery>,
+) -> Response {
+    if filter.confirm != Some(true) {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Bulk delete requires confirm=true".to_string(),
+        )
+            .into_response();
+    }
+    if !filter.has_filter() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Bulk delete requires at least one filter".to_string(),
+        )
+            .into_response();
+    }
+
+    match delete_articles_tx(&conn, &filter).await {
+        Ok(result) => (StatusCode::OK, Json(result)).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn delete_articles_tx(
+    conn: &Pool<Postgres>,
+    filter: &ArticleDeleteQuery,
+) -> Result<ArticleDeleteResult, sqlx::Error> {
+    let mut delete = QueryBuilder::<Postgres>::new(
+        "DELETE FROM article where id in (SELECT id FROM article where 1=1",
+    );
+    push_delete_filter(&mut delete, filter);
+    delete.push(" limit ").push_bind(MAX_BULK_DELETE).push(");");
+
+    let mut remaining = QueryBuilder::<Postgres>::new("SELECT count(*) FROM article where 1=1");
+    push_delete_filter(&mut remaining, filter);
+
+    let mut tx = conn.begin().await?;
+    let deleted = delete.build().execute(&mut *tx).await?.rows_affected();
+    let remaining: i64 = remaining.build_query_scalar().fetch_one(&mut *tx).await?;
+    tx.commit().await?;
+
+    Ok(ArticleDeleteResult { deleted, remaining })
+}
+
+fn push_delete_filter(query: &mut QueryBuilder<Postgres>, filter: &ArticleDeleteQuery) {
+    if let Some(feed_id) = filter.feed_id {
+        query.push(" and feed_id = ").push_bind(feed_id);
+    }
+    if let Some(unread_only) = filter.unread_only {
+        query.push(" and read = ").push_bind(!unread_only);
+    }
+    if let Some(published_after) = filter.published_after {
+        query.push(" and published >= ").push_bind(published_after);
+    }
+    if let Some(published_before) = filter.published_before {
+        query.push(" and published < ").push_bind(published_before);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{insert_article, insert_feed, json_body};
+
+    fn delete_query(feed_id: Option<Uuid>, unread_only: Option<bool>) -> ArticleDeleteQuery {
+        ArticleDeleteQuery {
+            feed_id,
+            unread_only,
+            published_after: None,
+            published_before: None,
+            confirm: Some(true),
+        }
+    }
+
+    async fn all_urls(conn: &PgPool) -> Vec<String> {
+        sqlx::query_scalar("SELECT url FROM article order by url;")
+            .fetch_all(conn)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn deletes_read_articles_of_one_feed(conn: PgPool) {
+        let feed = insert_feed(&conn, "http://example.com/a.xml").await;
+        let other = insert_feed(&conn, "http://example.com/b.xml").await;
+        insert_article(&conn, &feed, "http://example.com/a/read-1", true).await;
+        insert_article(&conn, &feed, "http://example.com/a/read-2", true).await;
+        insert_article(&conn, &feed, "http://example.com/a/unread", false).await;
+        insert_article(&conn, &other, "http://example.com/b/read", true).await;
+
+        let response = delete_articles(
+            Extension(conn.clone()),
+            Query(delete_query(Some(feed.id), Some(false))),
+        )
+        .await;
+
+        let result = json_body(response).await;
+        assert_eq!(result["deleted"], 2);
+        assert_eq!(result["remaining"], 0);
+        assert_eq!(
+            all_urls(&conn).await,
+            ["http://example.com/a/unread", "http://example.com/b/read"]
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn date_range_includes_after_and_excludes_before(conn: PgPool) {
+        let feed = insert_feed(&conn, "http://example.com/a.xml").await;
+        for (url, published) in [
+            ("http://example.com/a/earlier", "2024-01-14T23:59:59Z"),
+            ("http://example.com/a/on-after", "2024-01-15T00:00:00Z"),
+            ("http://example.com/a/between", "2024-01-15T12:00:00Z"),
+            ("http://example.com/a/on-before", "2024-01-16T00:00:00Z"),
+        ] {
+            sqlx::query("INSERT INTO article (id, feed_id, url, title, content, read, published) values (gen_random_uuid(), $1, $2, $2, '', false, $3::timestamptz);")
+                .bind(feed.id)
+                .bind(url)
+                .bind(published)
+                .execute(&conn)
+                .await
+                .unwrap();
+        }
+
+        let mut query = delete_query(None, None);
+        query.published_after = Some("2024-01-15T00:00:00Z".parse().unwrap());
+        query.published_before = Some("2024-01-16T00:00:00Z".parse().unwrap());
+        let response = delete_articles(Extension(conn.clone()), Query(query)).await;
+
+        let result = json_body(response).await;
+        assert_eq!(result["deleted"], 2);
+        assert_eq!(
+            all_urls(&conn).await,
+            [
+                "http://example.com/a/earlier",
+                "http://example.com/a/on-before"
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn reports_rows_left_past_the_cap(conn: PgPool) {
+        let feed = insert_feed(&conn, "http://example.com/a.xml").await;
+        sqlx::query("INSERT INTO article (id, feed_id, url, title, content, read, published) SELECT gen_random_uuid(), $1, 'http://example.com/' || n, 'a', '', true, now() FROM generate_series(1, $2) n;")
+            .bind(feed.id)
+            .bind(MAX_BULK_DELETE + 5)
+            .execute(&conn)
+            .await
+            .unwrap();
+
+        let response = delete_articles(
+            Extension(conn.clone()),
+            Query(delete_query(Some(feed.id), None)),
+        )
+        .await;
+
+        let result = json_body(response).await;
+        assert_eq!(result["deleted"], MAX_BULK_DELETE);
+        assert_eq!(result["remaining"], 5);
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn refuses_without_confirm_or_filter(conn: PgPool) {
+        let feed = insert_feed(&conn, "http://example.com/a.xml").await;
+        insert_article(&conn, &feed, "http://example.com/a/read", true).await;
+
+        let mut unconfirmed = delete_query(Some(feed.id), Some(false));
+        unconfirmed.confirm = None;
+        let response = delete_articles(Extension(conn.clone()), Query(unconfirmed)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response =
+            delete_articles(Extension(conn.clone()), Query(delete_query(None, None))).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        assert_eq!(all_urls(&conn).await, ["http://example.com/a/read"]);
+    }
+}
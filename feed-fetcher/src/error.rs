@@ -0,0 +1,35 @@
+// errors surfaced by the fetch -> parse -> insert pipeline
+
+use std::{error::Error, fmt};
+
+#[derive(Debug)]
+pub enum AppError {
+    Fetch(Box<dyn Error + Send + Sync>),
+    Parse(Box<dyn Error + Send + Sync>),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Fetch(err) => write!(f, "fetch failed: {}", err),
+            AppError::Parse(err) => write!(f, "parse failed: {}", err),
+            AppError::Database(err) => write!(f, "database error: {}", err),
+        }
+    }
+}
+
+impl Error for AppError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AppError::Fetch(err) | AppError::Parse(err) => Some(err.as_ref()),
+            AppError::Database(err) => Some(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        AppError::Database(err)
+    }
+}
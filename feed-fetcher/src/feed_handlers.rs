@@ -10,18 +10,40 @@ use axum::{
     response::{IntoResponse, Response},
 };
 
-use models::rest::Feed;
+use models::rest::{Feed, FeedMerge, FeedMergeResult, FeedMinPublished};
 
+#[utoipa::path(
+    post,
+    path = "/feeds",
+    request_body = Feed,
+    responses(
+        (status = 201, description = "Subscribed to feed"),
+        (status = 400, description = "Unknown timezone")
+    ),
+    tag = "feeds"
+)]
 pub async fn subscribe_feed(
     Extension(conn): Extension<Pool<Postgres>>,
     Json(body): Json<Feed>,
 ) -> Response {
+    if let Some(timezone) = &body.timezone
+        && timezone.parse::<chrono_tz::Tz>().is_err()
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Unknown timezone {}", timezone),
+        )
+            .into_response();
+    }
+
     let result = sqlx::query(
-        "INSERT INTO feed (id, url, title, description) values (gen_random_uuid(), $1, $2, $3);",
+        "INSERT INTO feed (id, url, title, description, min_published, timezone) values (gen_random_uuid(), $1, $2, $3, $4, $5);",
     )
     .bind(body.url.as_str())
     .bind(body.title.as_str())
     .bind(body.description)
+    .bind(body.min_published)
+    .bind(body.timezone)
     .execute(&conn)
     .await;
 
@@ -33,6 +55,12 @@ pub async fn subscribe_feed(
     (StatusCode::CREATED).into_response()
 }
 
+#[utoipa::path(
+    get,
+    path = "/feeds",
+    responses((status = 201, description = "Subscribed feeds", body = [models::db::Feed])),
+    tag = "feeds"
+)]
 pub async fn list_subscribed_feed(Extension(conn): Extension<Pool<Postgres>>) -> Response {
     let result = sqlx::query_as::<_, models::db::Feed>("SELECT * FROM feed;")
         .fetch_all(&conn)
@@ -44,6 +72,16 @@ pub async fn list_subscribed_feed(Extension(conn): Extension<Pool<Postgres>>) ->
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/feeds/{id}",
+    params(("id" = Uuid, Path, description = "Feed id")),
+    responses(
+        (status = 200, description = "Unsubscribed from feed"),
+        (status = 404, description = "Feed not found")
+    ),
+    tag = "feeds"
+)]
 pub async fn unsubscribe_feed(
     Path(id): Path<Uuid>,
     Extension(conn): Extension<Pool<Postgres>>,
@@ -64,3 +102,396 @@ pub async fn unsubscribe_feed(
         Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
     }
 }
+
+#[utoipa::path(
+    post,
+    path = "/feeds/{id}/min_published",
+    params(("id" = Uuid, Path, description = "Feed id")),
+    request_body = FeedMinPublished,
+    responses(
+        (status = 200, description = "Cutoff updated"),
+        (status = 404, description = "Feed not found")
+    ),
+    tag = "feeds"
+)]
+pub async fn set_min_published(
+    Path(id): Path<Uuid>,
+    Extension(conn): Extension<Pool<Postgres>>,
+    Json(body): Json<FeedMinPublished>,
+) -> Response {
+    let result = sqlx::query("UPDATE feed SET min_published = $1 where id = $2;")
+        .bind(body.min_published)
+        .bind(id)
+        .execute(&conn)
+        .await;
+
+    match result {
+        Ok(affected_rows) => {
+            if affected_rows.rows_affected() > 0 {
+                (StatusCode::OK, format!("Updated cutoff of feed {}", id)).into_response()
+            } else {
+                (StatusCode::NOT_FOUND, format!("feed {} not found", id)).into_response()
+            }
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/feeds/{id}/merge",
+    params(("id" = Uuid, Path, description = "Target feed id")),
+    request_body = FeedMerge,
+    responses(
+        (status = 200, description = "Feeds merged", body = FeedMergeResult),
+        (status = 400, description = "Source and target are the same feed"),
+        (status = 404, description = "Feed not found")
+    ),
+    tag = "feeds"
+)]
+pub async fn merge_feed(
+    Path(target_id): Path<Uuid>,
+    Extension(conn): Extension<Pool<Postgres>>,
+    Json(body): Json<FeedMerge>,
+) -> Response {
+    if body.source_id == target_id {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Cannot merge a feed into itself".to_string(),
+        )
+            .into_response();
+    }
+
+    match merge_feed_tx(&conn, target_id, body.source_id).await {
+        Ok(Some(merged)) => (StatusCode::OK, Json(merged)).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            format!("feed {} or {} not found", target_id, body.source_id),
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+async fn merge_feed_tx(
+    conn: &Pool<Postgres>,
+    target_id: Uuid,
+    source_id: Uuid,
+) -> Result<Option<FeedMergeResult>, sqlx::Error> {
+    let mut tx = conn.begin().await?;
+
+    // lock both feeds so a concurrent unsubscribe or merge waits for us
+    let found: Vec<Uuid> =
+        sqlx::query_scalar("SELECT id FROM feed where id = $1 or id = $2 FOR UPDATE;")
+            .bind(target_id)
+            .bind(source_id)
+            .fetch_all(&mut *tx)
+            .await?;
+    if found.len() < 2 {
+        return Ok(None);
+    }
+
+    // urls are unique across all feeds, so the same story can only show up
+    // under both feeds with different urls; match on title and date instead
+    let dropped = sqlx::query(
+        "DELETE FROM article s where s.feed_id = $1 and exists (SELECT 1 FROM article t where t.feed_id = $2 and t.title = s.title and t.published = s.published);",
+    )
+    .bind(source_id)
+    .bind(target_id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    let moved = sqlx::query("UPDATE article SET feed_id = $1 where feed_id = $2;")
+        .bind(target_id)
+        .bind(source_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    sqlx::query("DELETE FROM feed where id = $1;")
+        .bind(source_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(Some(FeedMergeResult { moved, dropped }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/feeds/{id}/mute",
+    params(("id" = Uuid, Path, description = "Feed id")),
+    responses(
+        (status = 200, description = "Feed muted"),
+        (status = 404, description = "Feed not found")
+    ),
+    tag = "feeds"
+)]
+pub async fn mute_feed(
+    Path(id): Path<Uuid>,
+    Extension(conn): Extension<Pool<Postgres>>,
+) -> Response {
+    set_feed_muted(&conn, id, true).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/feeds/{id}/unmute",
+    params(("id" = Uuid, Path, description = "Feed id")),
+    responses(
+        (status = 200, description = "Feed unmuted"),
+        (status = 404, description = "Feed not found")
+    ),
+    tag = "feeds"
+)]
+pub async fn unmute_feed(
+    Path(id): Path<Uuid>,
+    Extension(conn): Extension<Pool<Postgres>>,
+) -> Response {
+    set_feed_muted(&conn, id, false).await
+}
+
+async fn set_feed_muted(conn: &Pool<Postgres>, id: Uuid, muted: bool) -> Response {
+    let result = sqlx::query("UPDATE feed SET muted = $1 where id = $2;")
+        .bind(muted)
+        .bind(id)
+        .execute(conn)
+        .await;
+
+    let action = if muted { "muted" } else { "unmuted" };
+    match result {
+        Ok(affected_rows) => {
+            if affected_rows.rows_affected() > 0 {
+                (StatusCode::OK, format!("Feed {} {}", id, action)).into_response()
+            } else {
+                (StatusCode::NOT_FOUND, format!("feed {} not found", id)).into_response()
+            }
+        }
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::extract::Query;
+    use chrono::{DateTime, Utc};
+    use models::{
+        db::Feed,
+        rest::{ArticleQuery, ArticleViewQuery},
+    };
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        article_handlers::{list_articles, list_feed_articles},
+        test_utils::{insert_article, insert_feed, json_body, urls},
+    };
+
+    fn article_query(feed_id: Option<Uuid>, include_muted: Option<bool>) -> ArticleQuery {
+        ArticleQuery {
+            feed_id,
+            unread_only: None,
+            limit: None,
+            offset: None,
+            view: None,
+            include_muted,
+        }
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn muted_feed_is_hidden_from_global_listing_only(conn: PgPool) {
+        let loud = insert_feed(&conn, "http://example.com/loud.xml").await;
+        let quiet = insert_feed(&conn, "http://example.com/quiet.xml").await;
+        insert_article(&conn, &loud, "http://example.com/loud", false).await;
+        insert_article(&conn, &quiet, "http://example.com/quiet", false).await;
+
+        let response = mute_feed(Path(quiet.id), Extension(conn.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let global = list_articles(Extension(conn.clone()), Query(article_query(None, None))).await;
+        assert_eq!(urls(&json_body(global).await), ["http://example.com/loud"]);
+
+        let everything = list_articles(
+            Extension(conn.clone()),
+            Query(article_query(None, Some(true))),
+        )
+        .await;
+        assert_eq!(
+            urls(&json_body(everything).await),
+            ["http://example.com/loud", "http://example.com/quiet"]
+        );
+
+        let by_id = list_articles(
+            Extension(conn.clone()),
+            Query(article_query(Some(quiet.id), None)),
+        )
+        .await;
+        assert_eq!(urls(&json_body(by_id).await), ["http://example.com/quiet"]);
+
+        let per_feed = list_feed_articles(
+            Path(quiet.id),
+            Extension(conn.clone()),
+            Query(ArticleViewQuery { view: None }),
+        )
+        .await;
+        assert_eq!(
+            urls(&json_body(per_feed).await),
+            ["http://example.com/quiet"]
+        );
+
+        let response = unmute_feed(Path(quiet.id), Extension(conn.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let global = list_articles(Extension(conn.clone()), Query(article_query(None, None))).await;
+        assert_eq!(
+            urls(&json_body(global).await),
+            ["http://example.com/loud", "http://example.com/quiet"]
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn article_without_feed_is_not_dropped_by_mute_filter(conn: PgPool) {
+        sqlx::query("INSERT INTO article (id, feed_id, url, title, content, read, published) values (gen_random_uuid(), NULL, 'http://example.com/orphan', 'orphan', '', false, now());")
+            .execute(&conn)
+            .await
+            .unwrap();
+
+        let global = list_articles(Extension(conn.clone()), Query(article_query(None, None))).await;
+
+        assert_eq!(
+            urls(&json_body(global).await),
+            ["http://example.com/orphan"]
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn muting_unknown_feed_is_not_found(conn: PgPool) {
+        let response = mute_feed(Path(Uuid::new_v4()), Extension(conn)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn stored_min_published(conn: &PgPool, feed: &Feed) -> Option<DateTime<Utc>> {
+        sqlx::query_scalar("SELECT min_published FROM feed where id = $1;")
+            .bind(feed.id)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn min_published_can_be_set_and_cleared_on_existing_feed(conn: PgPool) {
+        let feed = insert_feed(&conn, "http://example.com/a.xml").await;
+        let cutoff: DateTime<Utc> = "2020-01-01T00:00:00Z".parse().unwrap();
+
+        let response = set_min_published(
+            Path(feed.id),
+            Extension(conn.clone()),
+            Json(FeedMinPublished {
+                min_published: Some(cutoff),
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stored_min_published(&conn, &feed).await, Some(cutoff));
+
+        let response = set_min_published(
+            Path(feed.id),
+            Extension(conn.clone()),
+            Json(FeedMinPublished {
+                min_published: None,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(stored_min_published(&conn, &feed).await, None);
+
+        let unknown = set_min_published(
+            Path(Uuid::new_v4()),
+            Extension(conn.clone()),
+            Json(FeedMinPublished {
+                min_published: None,
+            }),
+        )
+        .await;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn insert_dated_article(conn: &PgPool, feed: &Feed, url: &str, title: &str) {
+        sqlx::query("INSERT INTO article (id, feed_id, url, title, content, read, published) values (gen_random_uuid(), $1, $2, $3, '', false, '2024-01-15T10:00:00Z');")
+            .bind(feed.id)
+            .bind(url)
+            .bind(title)
+            .execute(conn)
+            .await
+            .unwrap();
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn merge_moves_distinct_and_drops_duplicate_articles(conn: PgPool) {
+        let target = insert_feed(&conn, "http://example.com/a.xml").await;
+        let source = insert_feed(&conn, "http://example.com/b.xml").await;
+        insert_dated_article(&conn, &target, "http://example.com/shared", "shared").await;
+        insert_dated_article(&conn, &target, "http://example.com/a-only", "a only").await;
+        insert_dated_article(&conn, &source, "http://example.com/shared?utm=b", "shared").await;
+        insert_dated_article(&conn, &source, "http://example.com/b-only", "b only").await;
+
+        let response = merge_feed(
+            Path(target.id),
+            Extension(conn.clone()),
+            Json(FeedMerge {
+                source_id: source.id,
+            }),
+        )
+        .await;
+
+        let result = json_body(response).await;
+        assert_eq!(result["moved"], 1);
+        assert_eq!(result["dropped"], 1);
+        let remaining_feeds: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM feed;")
+            .fetch_all(&conn)
+            .await
+            .unwrap();
+        assert_eq!(remaining_feeds, [target.id]);
+        let per_feed = list_feed_articles(
+            Path(target.id),
+            Extension(conn.clone()),
+            Query(ArticleViewQuery { view: None }),
+        )
+        .await;
+        assert_eq!(
+            urls(&json_body(per_feed).await),
+            [
+                "http://example.com/a-only",
+                "http://example.com/b-only",
+                "http://example.com/shared"
+            ]
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn merge_rejects_self_and_unknown_feeds(conn: PgPool) {
+        let target = insert_feed(&conn, "http://example.com/a.xml").await;
+
+        let into_self = merge_feed(
+            Path(target.id),
+            Extension(conn.clone()),
+            Json(FeedMerge {
+                source_id: target.id,
+            }),
+        )
+        .await;
+        assert_eq!(into_self.status(), StatusCode::BAD_REQUEST);
+
+        let unknown = merge_feed(
+            Path(target.id),
+            Extension(conn.clone()),
+            Json(FeedMerge {
+                source_id: Uuid::new_v4(),
+            }),
+        )
+        .await;
+        assert_eq!(unknown.status(), StatusCode::NOT_FOUND);
+    }
+}
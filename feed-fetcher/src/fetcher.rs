@@ -1,13 +1,27 @@
 // fetch articles
 
+use std::{error::Error, io};
+
+use futures_util::TryStreamExt;
 use models::db::Feed;
-use rss::Channel;
-use std::error::Error;
+use reqwest::Client;
+use tokio::io::AsyncBufRead;
+use tokio_util::io::StreamReader;
 
-pub async fn feed_fetcher(feed: &Feed) -> Result<Channel, Box<dyn Error + Send + Sync>> {
-    let r = reqwest::get(feed.url.as_str()).await?.bytes().await?;
+/// Feed document as a reader over the response body, so it can be parsed
+/// chunk by chunk while it downloads instead of being buffered whole.
+pub async fn fetch_feed_body(
+    client: &Client,
+    feed: &Feed,
+) -> Result<impl AsyncBufRead + Unpin + Send, Box<dyn Error + Send + Sync>> {
+    // an error page is not a feed document, even if it happens to parse
+    let r = client
+        .get(feed.url.as_str())
+        .send()
+        .await?
+        .error_for_status()?;
 
-    let channel = Channel::read_from(&r[..])?;
+    let chunks = r.bytes_stream().map_err(io::Error::other);
 
-    Ok(channel)
+    Ok(StreamReader::new(Box::pin(chunks)))
 }
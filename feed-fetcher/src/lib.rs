@@ -1,6 +1,11 @@
+pub mod error;
 pub mod fetcher;
 pub mod parser;
+pub mod reachability;
 pub mod worker;
 
 pub mod article_handlers;
 pub mod feed_handlers;
+
+#[cfg(test)]
+mod test_utils;
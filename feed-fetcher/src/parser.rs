@@ -1,40 +1,553 @@
 // Parse articles into
 
-use std::{cell::Cell, error::Error};
+use std::error::Error;
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 use models::{db::Feed, rest::Article};
-use rss::Channel;
-
-pub async fn feed_parser(
-    feed: &Feed,
-    channel: Channel,
-) -> Result<Vec<Article>, Box<dyn Error + Sync + Send>> {
-    let mut articles: Vec<Article> = vec![];
-
-    let default_pub_date = Utc::now();
-    let default_pub_date_str = Cell::new(default_pub_date.format("%Y-%m-%dT%H:%M:%SZ").to_string());
-
-    for article in channel.items() {
-        // println!("{:?}\n\n", article); // TODO remove
-        let pub_date = match NaiveDateTime::parse_from_str(
-            &article
-                .pub_date
-                .clone()
-                .unwrap_or(default_pub_date_str.take()),
-            "%Y-%m-%dT%H:%M:%SZ",
-        ) {
-            Ok(date) => date.and_utc(),
-            Err(_err) => default_pub_date,
+use quick_xml::{
+    Reader,
+    events::{BytesStart, Event},
+};
+use tokio::io::AsyncBufRead;
+
+// formats carrying no offset, read in the feed's timezone; the first is an
+// RFC 822 date with the zone left off, `%.f` also matches no fraction at all
+const NAIVE_DATE_FORMATS: [&str; 3] = [
+    "%a, %d %b %Y %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+];
+
+/// The feed's configured timezone, falling back to UTC when unset or unknown.
+pub fn feed_timezone(feed: &Feed) -> Tz {
+    feed.timezone
+        .as_deref()
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/// Parses RSS (RFC 2822) and Atom (RFC 3339) dates as given, only falling
+/// back to `timezone` for strings that carry no offset.
+pub fn parse_pub_date(raw: &str, timezone: Tz) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    if let Ok(date) = DateTime::parse_from_rfc2822(raw) {
+        return Some(date.with_timezone(&Utc));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(raw) {
+        return Some(date.with_timezone(&Utc));
+    }
+    NAIVE_DATE_FORMATS.iter().find_map(|format| {
+        let naive = NaiveDateTime::parse_from_str(raw, format).ok()?;
+        // DST gaps have no local time, overlaps pick the earlier one
+        timezone
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|date| date.with_timezone(&Utc))
+    })
+}
+
+/// Streams RSS `<item>`s and Atom `<entry>`s out of a feed document as it is
+/// read, so only the article being parsed is held in memory.
+pub fn parse_feed_items<R: AsyncBufRead + Unpin>(feed: &Feed, body: R) -> FeedItems<'_, R> {
+    let mut reader = Reader::from_reader(body);
+    reader.config_mut().trim_text(true);
+    FeedItems {
+        feed,
+        reader,
+        buf: Vec::new(),
+        timezone: feed_timezone(feed),
+        root_seen: false,
+    }
+}
+
+pub struct FeedItems<'a, R> {
+    feed: &'a Feed,
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    timezone: Tz,
+    root_seen: bool,
+}
+
+#[derive(Clone, Copy)]
+enum ItemField {
+    Title,
+    Link,
+    Description,
+    Content,
+    PubDate,
+    Updated,
+}
+
+#[derive(Default)]
+struct ItemFields {
+    title: Option<String>,
+    link: Option<String>,
+    description: Option<String>,
+    content: Option<String>,
+    pub_date: Option<String>,
+    updated: Option<String>,
+}
+
+impl ItemFields {
+    fn push(&mut self, field: ItemField, text: &str) {
+        let slot = match field {
+            ItemField::Title => &mut self.title,
+            ItemField::Link => &mut self.link,
+            ItemField::Description => &mut self.description,
+            ItemField::Content => &mut self.content,
+            ItemField::PubDate => &mut self.pub_date,
+            ItemField::Updated => &mut self.updated,
         };
-        articles.push(Article {
-            feed_id: feed.id,
-            title: article.title.clone().unwrap_or("".to_string()),
-            url: article.link.clone().unwrap_or("".to_string()),
+        slot.get_or_insert_with(String::new).push_str(text);
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> FeedItems<'_, R> {
+    /// The next article in the document, `None` once it is exhausted. A
+    /// document that is not RSS, RDF or Atom is an error, not an empty feed.
+    pub async fn next_article(&mut self) -> Option<Result<Article, Box<dyn Error + Sync + Send>>> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into_async(&mut self.buf).await {
+                Ok(Event::Start(tag) | Event::Empty(tag)) if !self.root_seen => {
+                    if !is_feed_root(tag.name().as_ref()) {
+                        let root = String::from_utf8_lossy(tag.name().as_ref()).into_owned();
+                        return Some(Err(
+                            format!("not a feed document, root is <{}>", root).into()
+                        ));
+                    }
+                    self.root_seen = true;
+                }
+                Ok(Event::Start(tag)) if is_item(tag.name().as_ref()) => {
+                    return Some(self.read_item().await);
+                }
+                Ok(Event::Eof) if !self.root_seen => {
+                    return Some(Err("not a feed document, no root element".into()));
+                }
+                Ok(Event::Eof) => return None,
+                Err(err) => return Some(Err(err.into())),
+                _ => {}
+            }
+        }
+    }
+
+    async fn read_item(&mut self) -> Result<Article, Box<dyn Error + Sync + Send>> {
+        let mut fields = ItemFields::default();
+        let mut current: Option<ItemField> = None;
+        // only direct children of the item fill its fields, so an Atom
+        // `<source>` or other nested block can't leak its title or dates in
+        let mut depth = 0usize;
+        loop {
+            self.buf.clear();
+            match self.reader.read_event_into_async(&mut self.buf).await? {
+                Event::Start(tag) => {
+                    if depth == 0 {
+                        if tag.name().as_ref() == b"link" {
+                            atom_link(&tag, &mut fields)?;
+                        }
+                        current = match tag.name().as_ref() {
+                            b"title" => Some(ItemField::Title),
+                            b"link" => Some(ItemField::Link),
+                            b"description" | b"summary" => Some(ItemField::Description),
+                            b"content:encoded" | b"content" => Some(ItemField::Content),
+                            b"pubDate" | b"published" => Some(ItemField::PubDate),
+                            b"updated" => Some(ItemField::Updated),
+                            _ => None,
+                        }
+                    }
+                    depth += 1;
+                }
+                Event::Empty(tag) if depth == 0 && tag.name().as_ref() == b"link" => {
+                    atom_link(&tag, &mut fields)?;
+                }
+                Event::Text(text) if depth == 1 => {
+                    if let Some(field) = current {
+                        fields.push(field, &text.unescape()?);
+                    }
+                }
+                Event::CData(data) if depth == 1 => {
+                    if let Some(field) = current {
+                        fields.push(field, &data.decode()?);
+                    }
+                }
+                Event::End(_) if depth == 0 => break,
+                Event::End(_) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        current = None;
+                    }
+                }
+                Event::Eof => return Err("unexpected end of feed inside an item".into()),
+                _ => {}
+            }
+        }
+
+        let pub_date = fields
+            .pub_date
+            .or(fields.updated)
+            .as_deref()
+            .and_then(|raw| parse_pub_date(raw, self.timezone));
+        Ok(Article {
+            feed_id: self.feed.id,
+            title: fields.title.unwrap_or("".to_string()),
+            url: fields.link.unwrap_or("".to_string()),
             published: pub_date,
-            content: article.content.clone().unwrap_or("".to_string()),
+            summary: fields.description,
+            content: fields.content.unwrap_or("".to_string()),
+        })
+    }
+}
+
+fn is_feed_root(name: &[u8]) -> bool {
+    name == b"rss" || name == b"rdf:RDF" || name == b"feed"
+}
+
+fn is_item(name: &[u8]) -> bool {
+    name == b"item" || name == b"entry"
+}
+
+// Atom puts the article url in `<link rel="alternate" href="..."/>`
+fn atom_link(
+    tag: &BytesStart,
+    fields: &mut ItemFields,
+) -> Result<(), Box<dyn Error + Sync + Send>> {
+    if fields.link.is_some() {
+        return Ok(());
+    }
+    let rel = tag.try_get_attribute("rel")?;
+    if rel.is_some_and(|rel| rel.value.as_ref() != b"alternate") {
+        return Ok(());
+    }
+    if let Some(href) = tag.try_get_attribute("href")? {
+        fields.link = Some(href.unescape_value()?.into_owned());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use chrono_tz::America::New_York;
+
+    use tokio::io::{AsyncWriteExt, BufReader};
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn test_feed() -> Feed {
+        Feed {
+            id: Uuid::new_v4(),
+            url: "http://example.com/feed.xml".to_string(),
+            title: "example".to_string(),
+            description: None,
+            active: true,
+            muted: false,
+            min_published: None,
+            reachable: true,
+            timezone: None,
+        }
+    }
+
+    async fn parse_all(feed: &Feed, body: &str) -> Vec<Article> {
+        let mut items = parse_feed_items(feed, body.as_bytes());
+        let mut articles = vec![];
+        while let Some(article) = items.next_article().await {
+            articles.push(article.unwrap());
+        }
+        articles
+    }
+
+    #[tokio::test]
+    async fn rss_item_with_cdata_and_entities() {
+        let feed = test_feed();
+        let body = r#"<?xml version="1.0"?>
+            <rss><channel><title>channel</title><link>http://example.com</link>
+                <item>
+                    <title>Fish &amp; Chips</title>
+                    <link>http://example.com/a</link>
+                    <description><![CDATA[<p>short</p>]]></description>
+                    <pubDate>Mon, 02 Jan 2006 15:04:05 -0700</pubDate>
+                    <category>food</category>
+                </item>
+            </channel></rss>"#;
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].feed_id, feed.id);
+        assert_eq!(articles[0].title, "Fish & Chips");
+        assert_eq!(articles[0].url, "http://example.com/a");
+        assert_eq!(articles[0].summary.as_deref(), Some("<p>short</p>"));
+        assert_eq!(
+            articles[0].published,
+            Some(Utc.with_ymd_and_hms(2006, 1, 2, 22, 4, 5).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn item_keeps_summary_and_content_apart() {
+        let feed = test_feed();
+        let body = r#"<rss xmlns:content="http://purl.org/rss/1.0/modules/content/"><channel>
+                <item>
+                    <title>both</title>
+                    <link>http://example.com/both</link>
+                    <description>the summary</description>
+                    <content:encoded><![CDATA[<p>the full content</p>]]></content:encoded>
+                </item>
+                <item>
+                    <title>summary only</title>
+                    <link>http://example.com/summary</link>
+                    <description>just a summary</description>
+                </item>
+            </channel></rss>"#;
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(articles[0].summary.as_deref(), Some("the summary"));
+        assert_eq!(articles[0].content, "<p>the full content</p>");
+        assert_eq!(articles[1].summary.as_deref(), Some("just a summary"));
+        assert_eq!(articles[1].content, "");
+    }
+
+    #[tokio::test]
+    async fn item_without_link_has_empty_url() {
+        let feed = test_feed();
+        let body = "<rss><channel><item><title>no link</title></item></channel></rss>";
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].title, "no link");
+        assert_eq!(articles[0].url, "");
+        assert_eq!(articles[0].published, None);
+    }
+
+    #[tokio::test]
+    async fn truncated_item_is_an_error() {
+        let feed = test_feed();
+        let body = "<rss><channel><item><title>cut off</title><link>http://example.com/a";
+        let mut items = parse_feed_items(&feed, body.as_bytes());
+
+        assert!(items.next_article().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn non_feed_bodies_are_errors() {
+        let feed = test_feed();
+        let html = "<!DOCTYPE html><html><body><h1>Not Found</h1></body></html>";
+
+        for body in [html, "Not Found", ""] {
+            let mut items = parse_feed_items(&feed, body.as_bytes());
+            assert!(items.next_article().await.unwrap().is_err(), "{body:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_feed_has_no_items() {
+        let feed = test_feed();
+
+        assert!(
+            parse_all(&feed, "<rss><channel></channel></rss>")
+                .await
+                .is_empty()
+        );
+        assert!(parse_all(&feed, "<feed/>").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn atom_entry() {
+        let feed = test_feed();
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>channel</title>
+                <link href="http://example.com/"/>
+                <entry>
+                    <title>Atom entry</title>
+                    <link rel="self" href="http://example.com/self"/>
+                    <link rel="alternate" href="http://example.com/entry?a=1&amp;b=2"/>
+                    <updated>2024-01-16T00:00:00Z</updated>
+                    <published>2024-01-15T09:00:00Z</published>
+                    <summary>short</summary>
+                    <content type="html">&lt;p&gt;long&lt;/p&gt;</content>
+                </entry>
+            </feed>"#;
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].title, "Atom entry");
+        assert_eq!(articles[0].url, "http://example.com/entry?a=1&b=2");
+        assert_eq!(articles[0].summary.as_deref(), Some("short"));
+        assert_eq!(articles[0].content, "<p>long</p>");
+        assert_eq!(
+            articles[0].published,
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn atom_entry_ignores_nested_source_fields() {
+        let feed = test_feed();
+        let body = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+                <entry>
+                    <title>t</title>
+                    <link href="http://example.com/t"/>
+                    <source>
+                        <title>src</title>
+                        <link href="http://example.com/src"/>
+                        <updated>2001-01-01T00:00:00Z</updated>
+                    </source>
+                    <updated>2024-01-16T00:00:00Z</updated>
+                </entry>
+            </feed>"#;
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].title, "t");
+        assert_eq!(articles[0].url, "http://example.com/t");
+        assert_eq!(
+            articles[0].published,
+            Some(Utc.with_ymd_and_hms(2024, 1, 16, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn large_feed_streams_item_by_item() {
+        const ITEMS: usize = 50_000;
+        let feed = test_feed();
+
+        // the writer blocks once the small pipe is full, so the document is
+        // never in memory whole and items have to be consumed as they arrive
+        let (mut writer, reader) = tokio::io::duplex(8 * 1024);
+        let producer = tokio::spawn(async move {
+            writer.write_all(b"<rss><channel>").await.unwrap();
+            for i in 0..ITEMS {
+                let item = format!(
+                    "<item><title>item {i}</title><link>http://example.com/{i}</link></item>"
+                );
+                writer.write_all(item.as_bytes()).await.unwrap();
+            }
+            writer.write_all(b"</channel></rss>").await.unwrap();
         });
+
+        let mut items = parse_feed_items(&feed, BufReader::new(reader));
+        let mut count = 0;
+        while let Some(article) = items.next_article().await {
+            let article = article.unwrap();
+            assert_eq!(article.url, format!("http://example.com/{count}"));
+            count += 1;
+        }
+        producer.await.unwrap();
+
+        assert_eq!(count, ITEMS);
+    }
+
+    #[test]
+    fn parses_rfc2822_with_offset() {
+        assert_eq!(
+            parse_pub_date("Mon, 02 Jan 2006 15:04:05 -0700", New_York),
+            Some(Utc.with_ymd_and_hms(2006, 1, 2, 22, 4, 5).unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_rfc3339_ignoring_feed_timezone() {
+        assert_eq!(
+            parse_pub_date("2024-01-15T09:00:00Z", New_York),
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 9, 0, 0).unwrap())
+        );
+        assert_eq!(
+            parse_pub_date("2024-01-15T09:00:00+05:30", New_York),
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 3, 30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn naive_date_uses_feed_timezone() {
+        // EST, UTC-5
+        assert_eq!(
+            parse_pub_date("2024-01-15T09:00:00", New_York),
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 14, 0, 0).unwrap())
+        );
+        // EDT, UTC-4
+        assert_eq!(
+            parse_pub_date("2024-07-15 09:00:00", New_York),
+            Some(Utc.with_ymd_and_hms(2024, 7, 15, 13, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn zoneless_rfc822_and_fractional_dates_use_feed_timezone() {
+        assert_eq!(
+            parse_pub_date("Mon, 02 Jan 2006 15:04:05", New_York),
+            Some(Utc.with_ymd_and_hms(2006, 1, 2, 20, 4, 5).unwrap())
+        );
+        assert_eq!(
+            parse_pub_date("2024-01-15T09:00:00.250", New_York),
+            Some(
+                Utc.with_ymd_and_hms(2024, 1, 15, 14, 0, 0).unwrap()
+                    + chrono::Duration::milliseconds(250)
+            )
+        );
+        assert_eq!(
+            parse_pub_date("2024-07-15 09:00:00.5", New_York),
+            Some(
+                Utc.with_ymd_and_hms(2024, 7, 15, 13, 0, 0).unwrap()
+                    + chrono::Duration::milliseconds(500)
+            )
+        );
     }
 
-    Ok(articles)
+    #[tokio::test]
+    async fn feed_items_use_the_feed_timezone() {
+        let mut feed = test_feed();
+        feed.timezone = Some("America/New_York".to_string());
+        let body = r#"<rss><channel>
+                <item><link>http://example.com/naive</link><pubDate>Mon, 15 Jan 2024 09:00:00</pubDate></item>
+                <item><link>http://example.com/offset</link><pubDate>Mon, 15 Jan 2024 09:00:00 +0000</pubDate></item>
+            </channel></rss>"#;
+
+        let articles = parse_all(&feed, body).await;
+
+        assert_eq!(
+            articles[0].published,
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 14, 0, 0).unwrap())
+        );
+        assert_eq!(
+            articles[1].published,
+            Some(Utc.with_ymd_and_hms(2024, 1, 15, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_feed_timezone_falls_back_to_utc() {
+        let mut feed = test_feed();
+        assert_eq!(feed_timezone(&feed), Tz::UTC);
+        feed.timezone = Some("America/New_York".to_string());
+        assert_eq!(feed_timezone(&feed), New_York);
+        feed.timezone = Some("Mars/Olympus_Mons".to_string());
+        assert_eq!(feed_timezone(&feed), Tz::UTC);
+    }
+
+    #[test]
+    fn naive_date_defaults_to_utc() {
+        assert_eq!(
+            parse_pub_date("2024-07-15 09:00:00", Tz::UTC),
+            Some(Utc.with_ymd_and_hms(2024, 7, 15, 9, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn naive_date_in_dst_gap_is_rejected() {
+        // clocks jump from 02:00 to 03:00 on 2024-03-10
+        assert_eq!(parse_pub_date("2024-03-10 02:30:00", New_York), None);
+    }
+
+    #[test]
+    fn unparseable_date_is_none() {
+        assert_eq!(parse_pub_date("yesterday", Tz::UTC), None);
+        assert_eq!(parse_pub_date("", Tz::UTC), None);
+    }
 }
@@ -0,0 +1,128 @@
+// periodic HEAD sweep flagging unreachable feeds
+
+use std::time::Duration;
+
+use models::db::Feed;
+use reqwest::{Client, StatusCode};
+use sqlx::{Pool, Postgres};
+use tokio::time::interval;
+
+use crate::error::AppError;
+
+pub async fn reachability_schedule(conn: Pool<Postgres>, period: Duration) {
+    let client = match Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Reachability check disabled! Error: {}", err);
+            return;
+        }
+    };
+    let mut interv = interval(period);
+    loop {
+        interv.tick().await;
+        bg_reachability_check(&conn, &client).await;
+    }
+}
+
+pub async fn bg_reachability_check(conn: &Pool<Postgres>, client: &Client) {
+    let result = sqlx::query_as::<_, Feed>("SELECT * FROM feed;")
+        .fetch_all(conn)
+        .await;
+
+    match result {
+        Ok(feeds) => {
+            for feed in &feeds {
+                if let Err(err) = check_feed_reachability(conn, client, feed).await {
+                    eprintln!("Error checking feed {}: {}", feed.id, err);
+                }
+            }
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+        }
+    };
+}
+
+/// Sends a HEAD request to the feed url and records whether it answered.
+pub async fn check_feed_reachability(
+    conn: &Pool<Postgres>,
+    client: &Client,
+    feed: &Feed,
+) -> Result<bool, AppError> {
+    let reachable = match client.head(feed.url.as_str()).send().await {
+        // some servers reject HEAD outright but are otherwise alive
+        Ok(resp) => resp.status().is_success() || resp.status() == StatusCode::METHOD_NOT_ALLOWED,
+        Err(_err) => false,
+    };
+
+    sqlx::query("UPDATE feed SET reachable = $1 where id = $2;")
+        .bind(reachable)
+        .bind(feed.id)
+        .execute(conn)
+        .await?;
+
+    Ok(reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{insert_feed, serve_feed};
+
+    async fn stored_reachable(conn: &PgPool, feed: &Feed) -> bool {
+        sqlx::query_scalar("SELECT reachable FROM feed where id = $1;")
+            .bind(feed.id)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+    }
+
+    async fn check(conn: &PgPool, status: StatusCode) -> (bool, bool) {
+        let url = serve_feed(status, String::new()).await;
+        let feed = insert_feed(conn, &url).await;
+        let reachable = check_feed_reachability(conn, &Client::new(), &feed)
+            .await
+            .unwrap();
+        (reachable, stored_reachable(conn, &feed).await)
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn not_found_is_unreachable(conn: PgPool) {
+        assert_eq!(check(&conn, StatusCode::NOT_FOUND).await, (false, false));
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn method_not_allowed_is_reachable(conn: PgPool) {
+        assert_eq!(
+            check(&conn, StatusCode::METHOD_NOT_ALLOWED).await,
+            (true, true)
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn ok_is_reachable(conn: PgPool) {
+        assert_eq!(check(&conn, StatusCode::OK).await, (true, true));
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn refused_connection_is_unreachable(conn: PgPool) {
+        // bind and drop a listener to get a port nothing is listening on
+        let port = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let feed = insert_feed(&conn, &format!("http://127.0.0.1:{}/feed.xml", port)).await;
+
+        let reachable = check_feed_reachability(&conn, &Client::new(), &feed)
+            .await
+            .unwrap();
+
+        assert!(!reachable);
+        assert!(!stored_reachable(&conn, &feed).await);
+    }
+}
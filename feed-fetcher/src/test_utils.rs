@@ -0,0 +1,71 @@
+// shared helpers for tests that need a feed server or stored feeds
+
+use axum::{Router, body::to_bytes, http::StatusCode, response::Response, routing::any};
+use models::db::Feed;
+use sqlx::{Pool, Postgres};
+use tokio::net::TcpListener;
+
+/// Serves `body` with `status` for any method on a local port, returning the feed url.
+pub async fn serve_feed(status: StatusCode, body: String) -> String {
+    let router = Router::new().route("/feed.xml", any(move || async move { (status, body) }));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    format!("http://{}/feed.xml", addr)
+}
+
+/// Wraps RSS `<item>` markup in a channel.
+pub fn rss(items: &[String]) -> String {
+    format!(
+        "<?xml version=\"1.0\"?><rss xmlns:content=\"http://purl.org/rss/1.0/modules/content/\"><channel><title>test</title>{}</channel></rss>",
+        items.concat()
+    )
+}
+
+pub fn rss_item(url: &str, title: &str, pub_date: Option<&str>) -> String {
+    let pub_date = pub_date
+        .map(|date| format!("<pubDate>{}</pubDate>", date))
+        .unwrap_or_default();
+    format!("<item><title>{title}</title><link>{url}</link>{pub_date}</item>")
+}
+
+pub async fn insert_feed(conn: &Pool<Postgres>, url: &str) -> Feed {
+    sqlx::query_as::<_, Feed>(
+        "INSERT INTO feed (id, url, title) values (gen_random_uuid(), $1, 'test') RETURNING *;",
+    )
+    .bind(url)
+    .fetch_one(conn)
+    .await
+    .unwrap()
+}
+
+pub async fn insert_article(conn: &Pool<Postgres>, feed: &Feed, url: &str, read: bool) {
+    sqlx::query(
+        "INSERT INTO article (id, feed_id, url, title, content, read, published) values (gen_random_uuid(), $1, $2, $2, '', $3, now());",
+    )
+    .bind(feed.id)
+    .bind(url)
+    .bind(read)
+    .execute(conn)
+    .await
+    .unwrap();
+}
+
+/// Reads a handler response as JSON, failing on a non-200 status.
+pub async fn json_body(response: Response) -> serde_json::Value {
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// The `url` of every article in a JSON array, sorted.
+pub fn urls(articles: &serde_json::Value) -> Vec<String> {
+    let mut urls: Vec<String> = articles
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|article| article["url"].as_str().unwrap().to_string())
+        .collect();
+    urls.sort();
+    urls
+}
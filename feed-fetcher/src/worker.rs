@@ -1,23 +1,64 @@
 // background worker for fetching articles
 
-use std::time::Duration;
+use std::{collections::HashSet, str::FromStr, time::Duration};
 
-use models::db::Feed;
-use sqlx::{Pool, Postgres};
+use models::{db::Feed, rest::Article};
+use reqwest::Client;
+use sqlx::{Pool, Postgres, QueryBuilder};
 use tokio::time::interval;
 
-use crate::{fetcher::feed_fetcher, parser::feed_parser};
+use crate::{error::AppError, fetcher::fetch_feed_body, parser::parse_feed_items};
 
-pub async fn worker_schedule(conn: Pool<Postgres>) {
+// articles per multi-row INSERT, bounding memory held for large feeds
+const INSERT_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FeedProcessResult {
+    pub inserted: u64,
+    /// Already stored articles overwritten under [`DuplicateStrategy::Update`].
+    pub updated: u64,
+    /// Already stored articles left untouched.
+    pub duplicates: u64,
+    /// Articles left out by the feed's `min_published` cutoff.
+    pub skipped: u64,
+    pub errored: u64,
+}
+
+/// What the worker does with an article whose url is already stored.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateStrategy {
+    /// Leave the stored row as is.
+    #[default]
+    Skip,
+    /// Overwrite the stored row with the freshly fetched article.
+    Update,
+    /// Leave the stored row as is, but tally duplicates per cycle.
+    Count,
+}
+
+impl FromStr for DuplicateStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "skip" => Ok(DuplicateStrategy::Skip),
+            "update" => Ok(DuplicateStrategy::Update),
+            "count" => Ok(DuplicateStrategy::Count),
+            other => Err(format!("unknown duplicate strategy {}", other)),
+        }
+    }
+}
+
+pub async fn worker_schedule(conn: Pool<Postgres>, strategy: DuplicateStrategy) {
     let mut interv = interval(Duration::from_secs(900));
     loop {
         interv.tick().await;
         let new_conn = conn.clone();
-        bg_article_fetcher(new_conn).await;
+        bg_article_fetcher(new_conn, strategy).await;
     }
 }
 
-pub async fn bg_article_fetcher(conn: Pool<Postgres>) {
+pub async fn bg_article_fetcher(conn: Pool<Postgres>, strategy: DuplicateStrategy) {
     println!("Worker running...");
     let result = sqlx::query_as::<_, Feed>("SELECT * FROM feed where active=true;")
         .fetch_all(&conn)
@@ -25,44 +66,16 @@ pub async fn bg_article_fetcher(conn: Pool<Postgres>) {
 
     match result {
         Ok(feeds) => {
+            let client = Client::new();
+            let mut duplicates = 0;
             for feed in &feeds {
-                match feed_fetcher(feed).await {
-                    Ok(ch) => match feed_parser(feed, ch).await {
-                        Ok(articles) => {
-                            for article in &articles {
-                                let result = sqlx::query(r"INSERT INTO public.article (id, feed_id, url, title, content, read, published) VALUES(gen_random_uuid(), $1, $2, $3, $4, false, $5) ON CONFLICT (url) DO NOTHING;")
-                                    .bind(feed.id)
-                                    .bind(&article.url)
-                                    .bind(&article.title)
-                                    .bind(&article.content)
-                                    .bind(article.published)
-                                    .execute(&conn)
-                                    .await;
-
-                                match result {
-                                    Ok(_affected_rows) => {
-                                        // if affected_rows.rows_affected() > 0 {
-                                        //     println!("Insert successful!");
-                                        // } else {
-                                        //     println!("Insert unsuccessful!");
-                                        // };
-                                    }
-                                    Err(err) => {
-                                        eprintln!("Insert unsuccessful! Error: {}", err)
-                                    }
-                                };
-                            }
-                        }
-                        Err(err) => {
-                            eprintln!("Error: {}", err);
-                            return;
-                        }
-                    },
-                    Err(err) => {
-                        eprintln!("Error: {}", err);
-                        return;
-                    }
-                };
+                match process_feed(&conn, &client, feed, strategy).await {
+                    Ok(counts) => duplicates += counts.duplicates,
+                    Err(err) => eprintln!("Error processing feed {}: {}", feed.id, err),
+                }
+            }
+            if strategy == DuplicateStrategy::Count {
+                println!("Worker saw {} duplicate articles this cycle", duplicates);
             }
         }
         Err(err) => {
@@ -71,3 +84,384 @@ pub async fn bg_article_fetcher(conn: Pool<Postgres>) {
     };
     println!("Worker sleeping for 15mins...");
 }
+
+/// Fetches, parses and stores a single feed's articles.
+pub async fn process_feed(
+    conn: &Pool<Postgres>,
+    client: &Client,
+    feed: &Feed,
+    strategy: DuplicateStrategy,
+) -> Result<FeedProcessResult, AppError> {
+    let body = fetch_feed_body(client, feed)
+        .await
+        .map_err(AppError::Fetch)?;
+
+    let mut counts = FeedProcessResult::default();
+    let mut items = parse_feed_items(feed, body);
+    let mut batch: Vec<Article> = Vec::with_capacity(INSERT_BATCH_SIZE);
+    let mut undated_skipped = 0;
+    loop {
+        batch.clear();
+        while let Some(article) = items.next_article().await {
+            let article = article.map_err(AppError::Parse)?;
+            // skip archive items older than the feed's cutoff; undated
+            // items can't be shown to be newer, so they are skipped too
+            if let Some(cutoff) = feed.min_published
+                && article.published.is_none_or(|published| published < cutoff)
+            {
+                if article.published.is_none() {
+                    undated_skipped += 1;
+                }
+                counts.skipped += 1;
+                continue;
+            }
+            batch.push(article);
+            if batch.len() == INSERT_BATCH_SIZE {
+                break;
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+        insert_batch(conn, feed, &batch, strategy, &mut counts).await;
+    }
+    if undated_skipped > 0 {
+        eprintln!(
+            "Skipped {} articles of feed {} with a missing or unreadable date under its min_published cutoff",
+            undated_skipped, feed.id
+        );
+    }
+
+    Ok(counts)
+}
+
+async fn insert_batch(
+    conn: &Pool<Postgres>,
+    feed: &Feed,
+    batch: &[Article],
+    strategy: DuplicateStrategy,
+    counts: &mut FeedProcessResult,
+) {
+    // a url repeated within one statement would make DO UPDATE fail outright
+    let mut seen = HashSet::new();
+    let unique: Vec<&Article> = batch
+        .iter()
+        .filter(|article| seen.insert(article.url.as_str()))
+        .collect();
+    counts.duplicates += (batch.len() - unique.len()) as u64;
+
+    // undated rows go in their own statement so an update keeps their stored date
+    let (dated, undated): (Vec<&Article>, Vec<&Article>) = unique
+        .into_iter()
+        .partition(|article| article.published.is_some());
+    for rows in [dated, undated] {
+        if rows.is_empty() {
+            continue;
+        }
+        if let Err(err) = insert_rows(conn, feed, &rows, strategy, counts).await {
+            // one bad row (say an over-long title) fails the whole statement,
+            // so retry row by row and only lose the rows that fail themselves
+            eprintln!(
+                "Batch insert unsuccessful, retrying row by row! Error: {}",
+                err
+            );
+            for article in rows {
+                if let Err(err) = insert_rows(conn, feed, &[article], strategy, counts).await {
+                    eprintln!("Insert unsuccessful! Error: {}", err);
+                    counts.errored += 1;
+                }
+            }
+        }
+    }
+}
+
+async fn insert_rows(
+    conn: &Pool<Postgres>,
+    feed: &Feed,
+    rows: &[&Article],
+    strategy: DuplicateStrategy,
+    counts: &mut FeedProcessResult,
+) -> Result<(), sqlx::Error> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        "INSERT INTO public.article (id, feed_id, url, title, summary, content, read, published) ",
+    );
+    query.push_values(rows, |mut row, article| {
+        row.push("gen_random_uuid()")
+            .push_bind(feed.id)
+            .push_bind(&article.url)
+            .push_bind(&article.title)
+            .push_bind(&article.summary)
+            .push_bind(&article.content)
+            .push("false");
+        match article.published {
+            Some(published) => row.push_bind(published),
+            None => row.push("now()"),
+        };
+    });
+
+    let total = rows.len() as u64;
+    match strategy {
+        DuplicateStrategy::Update => {
+            query.push(" ON CONFLICT (url) DO UPDATE SET title = EXCLUDED.title, summary = EXCLUDED.summary, content = EXCLUDED.content");
+            // an undated item was stamped with now(), which must not replace the stored date
+            if rows.iter().all(|article| article.published.is_some()) {
+                query.push(", published = EXCLUDED.published");
+            }
+            // xmax is only set on rows that existed before this statement
+            query.push(" RETURNING (xmax = 0);");
+            let rows = query.build_query_scalar::<bool>().fetch_all(conn).await?;
+            let inserted = rows.iter().filter(|inserted| **inserted).count() as u64;
+            counts.inserted += inserted;
+            counts.updated += rows.len() as u64 - inserted;
+        }
+        DuplicateStrategy::Skip | DuplicateStrategy::Count => {
+            query.push(" ON CONFLICT (url) DO NOTHING;");
+            let affected_rows = query.build().execute(conn).await?;
+            counts.inserted += affected_rows.rows_affected();
+            counts.duplicates += total - affected_rows.rows_affected();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use chrono::{DateTime, Utc};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::test_utils::{insert_feed, rss, rss_item, serve_feed};
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn process_feed_counts_inserted_and_errored(conn: PgPool) {
+        let too_long_title = "x".repeat(1001);
+        let body = rss(&[
+            rss_item("http://example.com/1", "one", None),
+            rss_item("http://example.com/2", &too_long_title, None),
+            rss_item("http://example.com/3", "three", None),
+        ]);
+        let url = serve_feed(StatusCode::OK, body).await;
+        let feed = insert_feed(&conn, &url).await;
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            counts,
+            FeedProcessResult {
+                inserted: 2,
+                errored: 1,
+                ..Default::default()
+            }
+        );
+        let stored: Vec<String> =
+            sqlx::query_scalar("SELECT url FROM article where feed_id = $1 order by url;")
+                .bind(feed.id)
+                .fetch_all(&conn)
+                .await
+                .unwrap();
+        assert_eq!(stored, ["http://example.com/1", "http://example.com/3"]);
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn process_feed_surfaces_parse_errors(conn: PgPool) {
+        let url = serve_feed(StatusCode::OK, "<rss><channel><item>".to_string()).await;
+        let feed = insert_feed(&conn, &url).await;
+
+        let result = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip).await;
+
+        assert!(matches!(result, Err(AppError::Parse(_))));
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn process_feed_rejects_error_responses(conn: PgPool) {
+        let body = rss(&[rss_item("http://example.com/1", "one", None)]);
+        let url = serve_feed(StatusCode::NOT_FOUND, body).await;
+        let feed = insert_feed(&conn, &url).await;
+
+        let result = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip).await;
+
+        assert!(matches!(result, Err(AppError::Fetch(_))));
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn process_feed_rejects_html_bodies(conn: PgPool) {
+        let body = "<!DOCTYPE html><html><body>Moved to our new site</body></html>";
+        let url = serve_feed(StatusCode::OK, body.to_string()).await;
+        let feed = insert_feed(&conn, &url).await;
+
+        let result = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip).await;
+
+        assert!(matches!(result, Err(AppError::Parse(_))));
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn process_feed_skips_items_before_min_published(conn: PgPool) {
+        let body = rss(&[
+            rss_item(
+                "http://example.com/old",
+                "old",
+                Some("Tue, 03 Jan 2012 10:00:00 +0000"),
+            ),
+            rss_item(
+                "http://example.com/new",
+                "new",
+                Some("Mon, 15 Jan 2024 10:00:00 +0000"),
+            ),
+            rss_item("http://example.com/undated", "undated", None),
+        ]);
+        let url = serve_feed(StatusCode::OK, body).await;
+        let mut feed = insert_feed(&conn, &url).await;
+        feed.min_published = Some("2020-01-01T00:00:00Z".parse().unwrap());
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip)
+            .await
+            .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        assert_eq!(counts.skipped, 2);
+        let stored: Vec<String> = sqlx::query_scalar("SELECT url FROM article where feed_id = $1;")
+            .bind(feed.id)
+            .fetch_all(&conn)
+            .await
+            .unwrap();
+        assert_eq!(stored, ["http://example.com/new"]);
+    }
+
+    // serves a feed whose first two articles are already stored as "stale"
+    // (one of them undated in the feed) plus one new article
+    async fn feed_with_duplicates(conn: &PgPool) -> Feed {
+        let body = rss(&[
+            rss_item("http://example.com/undated", "fresh", None),
+            rss_item(
+                "http://example.com/dated",
+                "fresh",
+                Some("Mon, 15 Jan 2024 10:00:00 +0000"),
+            ),
+            rss_item("http://example.com/new", "new", None),
+        ]);
+        let url = serve_feed(StatusCode::OK, body).await;
+        let feed = insert_feed(conn, &url).await;
+        for url in ["http://example.com/undated", "http://example.com/dated"] {
+            sqlx::query("INSERT INTO article (id, feed_id, url, title, content, read, published) values (gen_random_uuid(), $1, $2, 'stale', '', false, '2000-01-01T00:00:00Z');")
+                .bind(feed.id)
+                .bind(url)
+                .execute(conn)
+                .await
+                .unwrap();
+        }
+        feed
+    }
+
+    async fn stored(conn: &PgPool, url: &str) -> (String, DateTime<Utc>) {
+        sqlx::query_as("SELECT title, published FROM article where url = $1;")
+            .bind(url)
+            .fetch_one(conn)
+            .await
+            .unwrap()
+    }
+
+    fn date(raw: &str) -> DateTime<Utc> {
+        raw.parse().unwrap()
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn skip_strategy_leaves_duplicates_unchanged(conn: PgPool) {
+        let feed = feed_with_duplicates(&conn).await;
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Skip)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            counts,
+            FeedProcessResult {
+                inserted: 1,
+                duplicates: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stored(&conn, "http://example.com/dated").await,
+            ("stale".to_string(), date("2000-01-01T00:00:00Z"))
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn update_strategy_overwrites_duplicates(conn: PgPool) {
+        let feed = feed_with_duplicates(&conn).await;
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Update)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            counts,
+            FeedProcessResult {
+                inserted: 1,
+                updated: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stored(&conn, "http://example.com/dated").await,
+            ("fresh".to_string(), date("2024-01-15T10:00:00Z"))
+        );
+        // no date in the feed, so the stored one is kept
+        assert_eq!(
+            stored(&conn, "http://example.com/undated").await,
+            ("fresh".to_string(), date("2000-01-01T00:00:00Z"))
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn count_strategy_tallies_duplicates(conn: PgPool) {
+        let feed = feed_with_duplicates(&conn).await;
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Count)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            counts,
+            FeedProcessResult {
+                inserted: 1,
+                duplicates: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            stored(&conn, "http://example.com/undated").await,
+            ("stale".to_string(), date("2000-01-01T00:00:00Z"))
+        );
+    }
+
+    #[sqlx::test(migrations = false, fixtures("../../database/init.sql"))]
+    async fn repeated_url_within_a_feed_is_a_duplicate(conn: PgPool) {
+        let body = rss(&[
+            rss_item("http://example.com/1", "first", None),
+            rss_item("http://example.com/1", "again", None),
+        ]);
+        let url = serve_feed(StatusCode::OK, body).await;
+        let feed = insert_feed(&conn, &url).await;
+
+        let counts = process_feed(&conn, &Client::new(), &feed, DuplicateStrategy::Update)
+            .await
+            .unwrap();
+
+        assert_eq!(counts.inserted, 1);
+        assert_eq!(counts.duplicates, 1);
+        assert_eq!(counts.errored, 0);
+    }
+
+    #[test]
+    fn duplicate_strategy_from_str() {
+        assert_eq!("skip".parse(), Ok(DuplicateStrategy::Skip));
+        assert_eq!(" Update ".parse(), Ok(DuplicateStrategy::Update));
+        assert_eq!("COUNT".parse(), Ok(DuplicateStrategy::Count));
+        assert!("replace".parse::<DuplicateStrategy>().is_err());
+    }
+}
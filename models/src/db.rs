@@ -1,24 +1,90 @@
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 use sqlx::{FromRow, prelude::Type};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, FromRow)]
+use crate::rest::ContentView;
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
 pub struct Feed {
     pub id: Uuid,
     pub url: String,
     pub title: String,
     pub description: Option<String>,
     pub active: bool,
+    pub muted: bool,
+    pub min_published: Option<DateTime<Utc>>,
+    pub reachable: bool,
+    pub timezone: Option<String>,
 }
 
-#[derive(Debug, Serialize, FromRow, Type)]
+#[derive(Debug, Serialize, FromRow, Type, ToSchema)]
 pub struct Article {
     pub id: Uuid,
-    pub feed_id: Uuid,
+    pub feed_id: Option<Uuid>,
     pub url: String,
     pub title: String,
-    pub content: String,
+    /// Left out of the response when `view=content` was asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Left out of the response when `view=summary` was asked for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
     pub read: bool,
     pub published: DateTime<Utc>,
 }
+
+impl Article {
+    /// Drops the representation the client did not ask for.
+    pub fn with_view(mut self, view: Option<ContentView>) -> Self {
+        match view {
+            Some(ContentView::Summary) => self.content = None,
+            Some(ContentView::Content) => self.summary = None,
+            None => {}
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article() -> Article {
+        Article {
+            id: Uuid::nil(),
+            feed_id: Some(Uuid::nil()),
+            url: "http://example.com/a".to_string(),
+            title: "a".to_string(),
+            summary: Some("short".to_string()),
+            content: Some("long".to_string()),
+            read: false,
+            published: DateTime::UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn summary_view_leaves_out_content() {
+        let json = serde_json::to_value(article().with_view(Some(ContentView::Summary))).unwrap();
+
+        assert_eq!(json["summary"], "short");
+        assert!(json.get("content").is_none());
+    }
+
+    #[test]
+    fn content_view_leaves_out_summary() {
+        let json = serde_json::to_value(article().with_view(Some(ContentView::Content))).unwrap();
+
+        assert!(json.get("summary").is_none());
+        assert_eq!(json["content"], "long");
+    }
+
+    #[test]
+    fn no_view_keeps_both() {
+        let json = serde_json::to_value(article().with_view(None)).unwrap();
+
+        assert_eq!(json["summary"], "short");
+        assert_eq!(json["content"], "long");
+    }
+}
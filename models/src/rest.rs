@@ -1,12 +1,17 @@
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, ToSchema)]
+#[schema(as = NewFeed)]
 pub struct Feed {
     pub url: String,
     pub title: String,
     pub description: Option<String>,
+    pub min_published: Option<DateTime<Utc>>,
+    /// IANA name used for dates without an offset, UTC when unset.
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -14,14 +19,85 @@ pub struct Article {
     pub feed_id: Uuid,
     pub title: String,
     pub url: String,
-    pub published: DateTime<Utc>,
+    /// `None` when the item had no date or one that could not be parsed.
+    pub published: Option<DateTime<Utc>>,
+    pub summary: Option<String>,
     pub content: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ArticleQuery {
     pub feed_id: Option<Uuid>,
     pub unread_only: Option<bool>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    pub view: Option<ContentView>,
+    /// Articles of muted feeds are left out unless this is `true` or a
+    /// `feed_id` is given.
+    pub include_muted: Option<bool>,
+}
+
+/// Which content representation to return; both are returned when unset.
+#[derive(Debug, Deserialize, Clone, Copy, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentView {
+    Summary,
+    Content,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArticleDeleteQuery {
+    pub feed_id: Option<Uuid>,
+    /// Same meaning as on [`ArticleQuery`]: `true` matches unread articles,
+    /// `false` read ones.
+    pub unread_only: Option<bool>,
+    /// Inclusive: articles published at exactly this time are matched.
+    pub published_after: Option<DateTime<Utc>>,
+    /// Exclusive: articles published at exactly this time are kept.
+    pub published_before: Option<DateTime<Utc>>,
+    /// Must be `true` for anything to be deleted.
+    pub confirm: Option<bool>,
+}
+
+impl ArticleDeleteQuery {
+    pub fn has_filter(&self) -> bool {
+        self.feed_id.is_some()
+            || self.unread_only.is_some()
+            || self.published_after.is_some()
+            || self.published_before.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArticleDeleteResult {
+    pub deleted: u64,
+    /// Matching articles left over once the per-call cap was reached.
+    pub remaining: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArticleViewQuery {
+    pub view: Option<ContentView>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FeedMinPublished {
+    /// Items published before this are skipped when fetching; `null` clears it.
+    pub min_published: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct FeedMerge {
+    pub source_id: Uuid,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FeedMergeResult {
+    pub moved: u64,
+    /// Source articles dropped because the target already had one with the
+    /// same title and publish date.
+    pub dropped: u64,
 }
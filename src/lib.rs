@@ -3,6 +3,7 @@ use std::error::Error;
 
 use crate::routing::create_router;
 
+mod openapi;
 mod routing;
 
 pub async fn app() -> Result<(), Box<dyn Error>> {
@@ -0,0 +1,155 @@
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use feed_fetcher::{article_handlers, feed_handlers};
+use utoipa::OpenApi;
+
+use crate::routing;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "albatross", description = "RSS feed aggregator and reader API"),
+    paths(
+        routing::health_check,
+        feed_handlers::subscribe_feed,
+        feed_handlers::list_subscribed_feed,
+        feed_handlers::unsubscribe_feed,
+        feed_handlers::set_min_published,
+        feed_handlers::merge_feed,
+        feed_handlers::mute_feed,
+        feed_handlers::unmute_feed,
+        article_handlers::list_feed_articles,
+        article_handlers::list_articles,
+        article_handlers::get_article,
+        article_handlers::article_mark_read,
+        article_handlers::delete_articles,
+    ),
+    tags(
+        (name = "feeds", description = "Feed subscriptions"),
+        (name = "articles", description = "Fetched articles")
+    )
+)]
+pub struct ApiDoc;
+
+// swagger-ui assets load from the unpkg CDN, so the page needs network
+// access in the browser; /openapi.json itself is served locally
+const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8" />
+    <title>albatross API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+        window.onload = () => {
+            window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+        };
+    </script>
+</body>
+</html>
+"##;
+
+pub async fn openapi_json() -> Response {
+    (StatusCode::OK, Json(ApiDoc::openapi())).into_response()
+}
+
+pub async fn swagger_ui() -> Response {
+    (StatusCode::OK, Html(SWAGGER_UI_HTML)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use crate::routing::api_router;
+
+    const DOCUMENTED_PATHS: [&str; 12] = [
+        "/articles",
+        "/articles/delete",
+        "/articles/{id}",
+        "/articles/{id}/read",
+        "/feeds",
+        "/feeds/{id}",
+        "/feeds/{id}/articles",
+        "/feeds/{id}/merge",
+        "/feeds/{id}/min_published",
+        "/feeds/{id}/mute",
+        "/feeds/{id}/unmute",
+        "/health",
+    ];
+
+    async fn fetch_spec() -> (serde_json::Value, utoipa::openapi::OpenApi) {
+        let response = api_router()
+            .oneshot(Request::get("/openapi.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let raw: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let spec: utoipa::openapi::OpenApi = serde_json::from_slice(&body).unwrap();
+        (raw, spec)
+    }
+
+    #[tokio::test]
+    async fn openapi_json_is_an_openapi_3_document() {
+        let (raw, spec) = fetch_spec().await;
+
+        assert!(raw["openapi"].as_str().unwrap().starts_with("3."));
+        let paths: Vec<&str> = spec.paths.paths.keys().map(String::as_str).collect();
+        assert_eq!(paths, DOCUMENTED_PATHS);
+    }
+
+    #[tokio::test]
+    async fn documented_operations_are_routed() {
+        let (_raw, spec) = fetch_spec().await;
+
+        for (path, item) in &spec.paths.paths {
+            let uri = path.replace("{id}", "00000000-0000-0000-0000-000000000000");
+            let methods = [
+                (Method::GET, item.get.is_some()),
+                (Method::POST, item.post.is_some()),
+                (Method::PUT, item.put.is_some()),
+                (Method::DELETE, item.delete.is_some()),
+                (Method::PATCH, item.patch.is_some()),
+            ];
+            for (method, _) in methods.into_iter().filter(|(_, documented)| *documented) {
+                let request = Request::builder()
+                    .method(method.clone())
+                    .uri(&uri)
+                    .body(Body::empty())
+                    .unwrap();
+                let status = api_router().oneshot(request).await.unwrap().status();
+                assert_ne!(status, StatusCode::NOT_FOUND, "{} {}", method, path);
+                assert_ne!(
+                    status,
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {}",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn swagger_ui_points_at_the_spec() {
+        let response = api_router()
+            .oneshot(Request::get("/swagger-ui").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("/openapi.json"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use axum::{
     Extension, Router,
     http::StatusCode,
@@ -5,30 +7,78 @@ use axum::{
     routing::{get, post},
 };
 use database::pool::create_conn_pool;
-use feed_fetcher::feed_handlers::{list_subscribed_feed, subscribe_feed, unsubscribe_feed};
+use feed_fetcher::feed_handlers::{
+    list_subscribed_feed, merge_feed, mute_feed, set_min_published, subscribe_feed, unmute_feed,
+    unsubscribe_feed,
+};
 use feed_fetcher::{
-    article_handlers::{article_mark_read, get_article, list_articles, list_feed_articles},
-    worker::bg_article_fetcher,
+    article_handlers::{
+        article_mark_read, delete_articles, get_article, list_articles, list_feed_articles,
+    },
+    reachability::reachability_schedule,
+    worker::{DuplicateStrategy, bg_article_fetcher},
 };
 
-async fn health_check() -> Response {
+use crate::openapi::{openapi_json, swagger_ui};
+
+#[utoipa::path(get, path = "/health", responses((status = 200, description = "Service is up")))]
+pub(crate) async fn health_check() -> Response {
     (StatusCode::OK, "up and running").into_response()
 }
 
+// REACHABILITY_INTERVAL_SECS, defaults to an hourly sweep
+fn reachability_interval() -> Duration {
+    let secs = std::env::var("REACHABILITY_INTERVAL_SECS")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .filter(|secs| *secs > 0)
+        .unwrap_or(3600);
+    Duration::from_secs(secs)
+}
+
+// DUPLICATE_STRATEGY is one of skip (default), update or count
+fn duplicate_strategy() -> DuplicateStrategy {
+    match std::env::var("DUPLICATE_STRATEGY") {
+        Ok(strategy) => strategy.parse().unwrap_or_else(|err| {
+            eprintln!("{}, falling back to skip", err);
+            DuplicateStrategy::Skip
+        }),
+        Err(_err) => DuplicateStrategy::Skip,
+    }
+}
+
 pub async fn create_router() -> Router {
     let pool_conn = create_conn_pool().await;
 
     let worker_conn = pool_conn.clone();
-    tokio::spawn(async move { bg_article_fetcher(worker_conn).await });
+    let strategy = duplicate_strategy();
+    tokio::spawn(async move { bg_article_fetcher(worker_conn, strategy).await });
+
+    let reachability_conn = pool_conn.clone();
+    let reachability_period = reachability_interval();
+    tokio::spawn(
+        async move { reachability_schedule(reachability_conn, reachability_period).await },
+    );
+
+    api_router().layer(Extension(pool_conn.clone()))
+}
 
+// the routes alone, without the connection pool and workers
+pub(crate) fn api_router() -> Router {
     Router::new()
         .route("/health", get(health_check))
         .route("/feeds", post(subscribe_feed))
         .route("/feeds", get(list_subscribed_feed))
         .route("/feeds/{id}", post(unsubscribe_feed))
         .route("/feeds/{id}/articles", get(list_feed_articles))
+        .route("/feeds/{id}/merge", post(merge_feed))
+        .route("/feeds/{id}/min_published", post(set_min_published))
+        .route("/feeds/{id}/mute", post(mute_feed))
+        .route("/feeds/{id}/unmute", post(unmute_feed))
         .route("/articles", get(list_articles))
+        .route("/articles/delete", post(delete_articles))
         .route("/articles/{id}", get(get_article))
         .route("/articles/{id}/read", post(article_mark_read))
-        .layer(Extension(pool_conn.clone()))
+        .route("/openapi.json", get(openapi_json))
+        .route("/swagger-ui", get(swagger_ui))
 }